@@ -1,6 +1,6 @@
 use core::{fmt, result::Result};
 use std::collections::HashMap;
-use std::str;
+use std::io::{self, BufReader, Read};
 
 //
 //  Value stuff
@@ -27,46 +27,272 @@ pub enum ErrorType {
     ExpectedDictKey,
     ExpectedDictColonAfterKey { key: String },
     ExpectedDictCloseOrComma,
+    InvalidComment,
+    InvalidEscape { escape: u8 },
+    InvalidNumber { literal: String },
+    InvalidUnicodeEscape,
+    InvalidUtf8,
+    Io(io::Error),
+    MaxDepthExceeded,
+    TrailingCharacters,
     UnexpectedEndOfFile,
-    UnknownKeyword { keyword: String }
+    UnknownKeyword { keyword: String },
+    UnpairedSurrogate,
 }
 
 pub struct Error {
     pub line: usize,
+    pub column: usize,
     pub error: ErrorType,
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?} on line {}", self.error, self.line)
+        write!(f, "{:?} at line {}, column {}", self.error, self.line, self.column)
     }
 }
 
 //
-//  Parser stuff
+//  Source stuff
 //
+// A Source hands the parser one byte at a time with a single byte of lookahead, and tracks
+// line/column as it goes. This lets the same parsing logic below run over a fully buffered
+// slice or over an arbitrary byte iterator without the parser ever indexing into a buffer.
 #[allow(dead_code)]
-struct Parser<'a> {
-    line: usize,
-    caret: usize,
+trait Source {
+    fn peek(&self) -> Result<u8, Error>;
+    fn advance(&mut self) -> Result<u8, Error>;
+    fn error(&self, error: ErrorType) -> Error;
+}
+
+#[allow(dead_code)]
+struct SliceSource<'a> {
     document: &'a [u8],
+    caret: usize,
+    line: usize,
+    column: usize,
+}
+
+#[allow(dead_code)]
+impl<'a> SliceSource<'a> {
+    fn new(document: &'a [u8]) -> SliceSource<'a> {
+        SliceSource {
+            document,
+            caret: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+}
+
+impl Source for SliceSource<'_> {
+    fn peek(&self) -> Result<u8, Error> {
+        if self.caret >= self.document.len() {
+            return Err(self.error(ErrorType::UnexpectedEndOfFile));
+        }
+        Ok(self.document[self.caret])
+    }
+
+    fn advance(&mut self) -> Result<u8, Error> {
+        let ch = self.peek()?;
+        self.caret += 1;
+
+        if ch == b'\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+
+        Ok(ch)
+    }
+
+    fn error(&self, error: ErrorType) -> Error {
+        Error {
+            line: self.line,
+            column: self.column,
+            error,
+        }
+    }
+}
+
+#[allow(dead_code)]
+struct IterSource<I: Iterator<Item = u8>> {
+    bytes: I,
+    lookahead: Option<u8>,
+    line: usize,
+    column: usize,
+}
+
+#[allow(dead_code)]
+impl<I: Iterator<Item = u8>> IterSource<I> {
+    fn new(bytes: I) -> IterSource<I> {
+        let mut source = IterSource {
+            bytes,
+            lookahead: None,
+            line: 1,
+            column: 1,
+        };
+        source.lookahead = source.bytes.next();
+        source
+    }
+}
+
+impl<I: Iterator<Item = u8>> Source for IterSource<I> {
+    fn peek(&self) -> Result<u8, Error> {
+        self.lookahead.ok_or_else(|| self.error(ErrorType::UnexpectedEndOfFile))
+    }
+
+    fn advance(&mut self) -> Result<u8, Error> {
+        let ch = self.peek()?;
+        self.lookahead = self.bytes.next();
+
+        if ch == b'\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+
+        Ok(ch)
+    }
+
+    fn error(&self, error: ErrorType) -> Error {
+        Error {
+            line: self.line,
+            column: self.column,
+            error,
+        }
+    }
+}
+
+// wraps an arbitrary Read in a BufReader internally, so callers don't have to buffer their own
+// reader just to satisfy us, and reads one byte at a time through that buffer rather than
+// issuing a syscall per byte
+#[allow(dead_code)]
+struct ReaderSource<R: Read> {
+    reader: BufReader<R>,
+    lookahead: Option<u8>,
+    line: usize,
+    column: usize,
 }
 
 #[allow(dead_code)]
-impl Parser<'_> {
+impl<R: Read> ReaderSource<R> {
+    fn new(reader: R) -> Result<ReaderSource<R>, Error> {
+        let mut source = ReaderSource {
+            reader: BufReader::new(reader),
+            lookahead: None,
+            line: 1,
+            column: 1,
+        };
+        source.lookahead = source.read_one()?;
+        Ok(source)
+    }
+
+    // reads the next byte, retrying on a spurious interruption and reporting any other I/O
+    // failure as ErrorType::Io instead of letting the caller unwrap a panic out of it
+    fn read_one(&mut self) -> Result<Option<u8>, Error> {
+        let mut byte = [0u8; 1];
+        loop {
+            match self.reader.read(&mut byte) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(byte[0])),
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(Error { line: self.line, column: self.column, error: ErrorType::Io(error) }),
+            }
+        }
+    }
+}
+
+impl<R: Read> Source for ReaderSource<R> {
+    fn peek(&self) -> Result<u8, Error> {
+        self.lookahead.ok_or_else(|| self.error(ErrorType::UnexpectedEndOfFile))
+    }
+
+    fn advance(&mut self) -> Result<u8, Error> {
+        let ch = self.peek()?;
+        self.lookahead = self.read_one()?;
+
+        if ch == b'\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+
+        Ok(ch)
+    }
+
+    fn error(&self, error: ErrorType) -> Error {
+        Error {
+            line: self.line,
+            column: self.column,
+            error,
+        }
+    }
+}
+
+//
+//  Options stuff
+//
+// Controls grammar extensions beyond strict JSON; see parse_hjson_string below.
+#[derive(Clone, Copy, Default)]
+pub struct ParseOptions {
+    pub allow_comments: bool,
+    pub allow_unquoted_keys: bool,
+    pub allow_trailing_commas: bool,
+}
+
+impl ParseOptions {
+    // the relaxed, config-file-friendly grammar: comments, unquoted keys, and trailing
+    // commas before a closing ']' or '}'
+    pub fn hjson() -> ParseOptions {
+        ParseOptions {
+            allow_comments: true,
+            allow_unquoted_keys: true,
+            allow_trailing_commas: true,
+        }
+    }
+}
+
+//
+//  Parser stuff
+//
+const DEFAULT_MAX_DEPTH: usize = 256;
+
+#[allow(dead_code)]
+struct Parser<S: Source> {
+    source: S,
+    depth: usize,
+    max_depth: usize,
+    options: ParseOptions,
+}
+
+#[allow(dead_code)]
+impl<S: Source> Parser<S> {
     // constructor
-    fn new<'a>(document: &'a String) -> Parser<'a> {
+    fn new(source: S) -> Parser<S> {
+        Parser::with_options(source, DEFAULT_MAX_DEPTH, ParseOptions::default())
+    }
+
+    fn with_max_depth(source: S, max_depth: usize) -> Parser<S> {
+        Parser::with_options(source, max_depth, ParseOptions::default())
+    }
+
+    fn with_options(source: S, max_depth: usize, options: ParseOptions) -> Parser<S> {
         Parser {
-            line: 0,
-            caret: 0,
-            document: document.as_bytes(),
+            source,
+            depth: 0,
+            max_depth,
+            options,
         }
     }
 
     // entry function
     fn parse(&mut self) -> Result<Value, Error> {
         self.skip_whitespace()?;
-        
+
         // println!("entering parse, seeing: '{}'", char::from(self.peek()?));
         match self.peek()? {
             b'{' => self.dict(),
@@ -81,73 +307,148 @@ impl Parser<'_> {
 
     // specific parsers
     fn array(&mut self) -> Result<Value, Error> {
+        self.enter_nested()?;
         self.advance()?;
         let mut array: Vec<Value> = Vec::new();
 
+        self.skip_whitespace()?;
+        if self.check(b']')? {
+            self.depth -= 1;
+            return Ok(Value::Array { value: array });
+        }
+
         loop {
+            array.push(self.parse()?);
             self.skip_whitespace()?;
 
             if self.check(b']')? {
+                self.depth -= 1;
                 return Ok(Value::Array { value: array });
             }
 
-            array.push(self.parse()?);
+            if !self.check(b',')? {
+                return Err(self.error(ErrorType::ExpectedArrayCloseOrComma));
+            }
 
             self.skip_whitespace()?;
-            if self.peek()? != b']' && !self.check(b',')? {
-                return Err(self.error(ErrorType::ExpectedArrayCloseOrComma));
+            if self.check(b']')? {
+                if !self.options.allow_trailing_commas {
+                    return Err(self.error(ErrorType::ExpectedArrayCloseOrComma));
+                }
+
+                self.depth -= 1;
+                return Ok(Value::Array { value: array });
             }
         }
     }
 
     fn number(&mut self) -> Result<Value, Error> {
-        let start = self.caret;
-        self.check(b'-')?;
+        let mut literal = String::new();
+
+        if self.try_consume(b'-')? {
+            literal.push('-');
+            self.expect_digit(&literal)?;
+        }
 
         if self.peek()? == b'0' {
-            self.advance()?;
+            literal.push(char::from(self.advance()?));
         } else {
-            while self.peek()?.is_ascii_digit() {
-                self.advance()?;
+            self.consume_digits(&mut literal)?;
+        }
+
+        let mut is_float = false;
+
+        if self.try_consume(b'.')? {
+            is_float = true;
+            literal.push('.');
+            self.expect_digit(&literal)?;
+            self.consume_digits(&mut literal)?;
+        }
+
+        if self.try_consume(b'e')? || self.try_consume(b'E')? {
+            is_float = true;
+            literal.push('e');
+
+            if self.try_consume(b'+')? {
+                literal.push('+');
+            } else if self.try_consume(b'-')? {
+                literal.push('-');
             }
+
+            self.expect_digit(&literal)?;
+            self.consume_digits(&mut literal)?;
         }
 
-        if !self.check(b'.')? { // no dot so integer
-            let as_str = str::from_utf8(&self.document[start..self.caret]).unwrap();
-            return Ok(Value::Integer { value: as_str.parse().unwrap() });
+        if is_float {
+            return self.finite_float(literal);
         }
 
-        while self.peek()?.is_ascii_digit() {
-            self.advance()?;
+        match literal.parse::<i64>() {
+            Ok(value) => Ok(Value::Integer { value }),
+            // too big for an i64 but still a well-formed number literal: widen to a float
+            // instead of erroring, the same way most JSON libraries handle huge integers
+            Err(_) => self.finite_float(literal),
         }
-        
-        if self.check(b'e')? || self.check(b'E')? {
-            while self.peek()?.is_ascii_digit() {
-                self.advance()?;
-            }
+    }
+
+    // JSON has no representation for NaN or +/-Infinity, so a literal that parses to one
+    // of those (e.g. "1e400", which overflows f64) is a malformed number, not a huge float
+    fn finite_float(&self, literal: String) -> Result<Value, Error> {
+        match literal.parse::<f64>() {
+            Ok(value) if value.is_finite() => Ok(Value::Float { value }),
+            _ => Err(self.error(ErrorType::InvalidNumber { literal })),
+        }
+    }
+
+    // consumes digits up to the next non-digit byte or end of input, whichever comes first
+    fn consume_digits(&mut self, literal: &mut String) -> Result<(), Error> {
+        while matches!(self.peek(), Ok(byte) if byte.is_ascii_digit()) {
+            literal.push(char::from(self.advance()?));
         }
+        Ok(())
+    }
 
-        let as_str = str::from_utf8(&self.document[start..self.caret]).unwrap();
-        Ok(Value::Float { value: as_str.parse().unwrap() })
+    // like `check`, but treats end of input as "didn't match" rather than an error; numbers
+    // are the only grammar production that has to peek past its last byte, which may be EOF
+    fn try_consume(&mut self, expected: u8) -> Result<bool, Error> {
+        if !matches!(self.peek(), Ok(byte) if byte == expected) {
+            return Ok(false);
+        }
+
+        self.advance()?;
+        Ok(true)
+    }
+
+    fn expect_digit(&self, literal: &str) -> Result<(), Error> {
+        if matches!(self.peek(), Ok(byte) if byte.is_ascii_digit()) {
+            return Ok(());
+        }
+
+        Err(self.error(ErrorType::InvalidNumber { literal: literal.to_string() }))
     }
 
     fn dict(&mut self) -> Result<Value, Error> {
+        self.enter_nested()?;
         self.advance()?; // skip '{'
         let mut dict: HashMap<String, Value> = HashMap::new();
 
+        self.skip_whitespace()?;
+        if self.check(b'}')? {
+            self.depth -= 1;
+            return Ok(Value::Dict { value: dict });
+        }
+
         loop {
             self.skip_whitespace()?;
 
-            if self.check(b'}')? {
-                return Ok(Value::Dict { value: dict });
-            }
-
-            if !self.peek()? == b'"' {
+            let key = if self.peek()? == b'"' {
+                self.string()?
+            } else if self.options.allow_unquoted_keys {
+                self.unquoted_key()?
+            } else {
                 return Err(self.error(ErrorType::ExpectedDictKey));
-            }
-            
-            let key = self.string()?;
-            
+            };
+
             self.skip_whitespace()?;
 
             if !self.check(b':')? {
@@ -158,27 +459,105 @@ impl Parser<'_> {
             dict.insert(key, self.parse()?);
             self.skip_whitespace()?;
 
-            if self.peek()? != b'}' && !self.check(b',')? {
+            if self.check(b'}')? {
+                self.depth -= 1;
+                return Ok(Value::Dict { value: dict });
+            }
+
+            if !self.check(b',')? {
                 return Err(self.error(ErrorType::ExpectedDictCloseOrComma));
             }
+
+            self.skip_whitespace()?;
+            if self.check(b'}')? {
+                if !self.options.allow_trailing_commas {
+                    return Err(self.error(ErrorType::ExpectedDictCloseOrComma));
+                }
+
+                self.depth -= 1;
+                return Ok(Value::Dict { value: dict });
+            }
         }
     }
 
     fn string(&mut self) -> Result<String, Error> {
         self.advance()?;
-        let start = self.caret;
-        
+        let mut buffer: Vec<u8> = Vec::new();
+
         loop {
             if self.check(b'"')? {
-                // this can probably be from_utf8_unchecked but what do I know, lets leave unsafe for what it
-                // is for now
-                let string = String::from_utf8(self.document[start..(self.caret - 1)].to_vec()).unwrap();
-                // println!("Parsed string: '{}'", string);
-                return Ok(string);
+                // println!("Parsed string: '{}'", String::from_utf8_lossy(&buffer));
+                return String::from_utf8(buffer).map_err(|_| self.error(ErrorType::InvalidUtf8));
+            }
+
+            if self.check(b'\\')? {
+                let decoded = match self.advance()? {
+                    b'"' => '"',
+                    b'\\' => '\\',
+                    b'/' => '/',
+                    b'b' => '\u{0008}',
+                    b'f' => '\u{000C}',
+                    b'n' => '\n',
+                    b'r' => '\r',
+                    b't' => '\t',
+                    b'u' => self.unicode_escape()?,
+                    other => return Err(self.error(ErrorType::InvalidEscape { escape: other })),
+                };
+
+                let mut encoded = [0u8; 4];
+                buffer.extend_from_slice(decoded.encode_utf8(&mut encoded).as_bytes());
+                continue;
+            }
+
+            buffer.push(self.advance()?);
+        }
+    }
+
+    fn unquoted_key(&mut self) -> Result<String, Error> {
+        let mut key = String::new();
+
+        while matches!(self.peek(), Ok(byte) if byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'-') {
+            key.push(char::from(self.advance()?));
+        }
+
+        if key.is_empty() {
+            return Err(self.error(ErrorType::ExpectedDictKey));
+        }
+
+        Ok(key)
+    }
+
+    fn unicode_escape(&mut self) -> Result<char, Error> {
+        let unit = self.hex_u16()?;
+
+        if let 0xD800..=0xDBFF = unit {
+            if !self.check(b'\\')? || !self.check(b'u')? {
+                return Err(self.error(ErrorType::UnpairedSurrogate));
+            }
+
+            let low = self.hex_u16()?;
+            if !(0xDC00..=0xDFFF).contains(&low) {
+                return Err(self.error(ErrorType::UnpairedSurrogate));
             }
-            self.check(b'\\')?;
-            self.caret += 1;
+
+            let combined = ((unit - 0xD800) as u32) << 10 | (low - 0xDC00) as u32;
+            return char::from_u32(combined + 0x10000).ok_or_else(|| self.error(ErrorType::InvalidUnicodeEscape));
+        }
+
+        if (0xDC00..=0xDFFF).contains(&unit) {
+            return Err(self.error(ErrorType::UnpairedSurrogate));
         }
+
+        char::from_u32(unit as u32).ok_or_else(|| self.error(ErrorType::InvalidUnicodeEscape))
+    }
+
+    fn hex_u16(&mut self) -> Result<u16, Error> {
+        let mut digits = String::with_capacity(4);
+        for _ in 0..4 {
+            digits.push(char::from(self.advance()?));
+        }
+
+        u16::from_str_radix(&digits, 16).map_err(|_| self.error(ErrorType::InvalidUnicodeEscape))
     }
 
     fn word(&mut self, characters: &[u8]) -> Result<(), Error> {
@@ -187,28 +566,30 @@ impl Parser<'_> {
                 return Err(self.error(ErrorType::UnknownKeyword { keyword: String::from_utf8(characters.to_vec()).unwrap() }));
             }
         }
-        
+
         // println!("parsed keyword!");
         Ok(())
     }
 
     // utility
+    fn enter_nested(&mut self) -> Result<(), Error> {
+        if self.depth >= self.max_depth {
+            return Err(self.error(ErrorType::MaxDepthExceeded));
+        }
+
+        self.depth += 1;
+        Ok(())
+    }
+
     fn advance(&mut self) -> Result<u8, Error> {
-        let ch = self.peek()?;
-        self.caret += 1;
-        Ok(ch)
+        self.source.advance()
     }
 
     fn peek(&self) -> Result<u8, Error> {
-        if self.caret >= self.document.len() {
-            return Err(self.error(ErrorType::UnexpectedEndOfFile));
-        }
-        // println!(" peek: i: {:03}, {}", self.caret, char::from(self.document[self.caret]));
-        Ok(self.document[self.caret])
+        self.source.peek()
     }
 
     fn check(&mut self, expected: u8) -> Result<bool, Error> {
-        // println!("check: i: {}, '{}' (?: '{}')", self.caret, char::from(self.document[self.caret]), char::from(expected));
         if self.peek()? != expected {
             return Ok(false);
         }
@@ -218,24 +599,59 @@ impl Parser<'_> {
     }
 
     fn error(&self, error: ErrorType) -> Error {
-        Error {
-            line: self.line,
-            error: error,
+        self.source.error(error)
+    }
+
+    fn expect_eof(&mut self) -> Result<(), Error> {
+        self.skip_whitespace()?;
+
+        match self.peek() {
+            Err(Error { error: ErrorType::UnexpectedEndOfFile, .. }) => Ok(()),
+            Err(error) => Err(error),
+            Ok(_) => Err(self.error(ErrorType::TrailingCharacters)),
         }
     }
 
+    // skips whitespace and, when enabled, comments
     fn skip_whitespace(&mut self) -> Result<(), Error> {
         loop {
-            match self.peek()? {
-                b' ' => self.caret += 1,
-                b'\t' => self.caret += 1,
-                b'\n' => {
-                    self.caret += 1;
-                    self.line += 1
-                }
-                _ => break,
+            let byte = match self.peek() {
+                Ok(byte) => byte,
+                Err(_) => return Ok(()),
+            };
+
+            match byte {
+                b' ' | b'\t' | b'\n' => { self.advance()?; }
+                b'/' if self.options.allow_comments => self.skip_comment()?,
+                b'#' if self.options.allow_comments => self.skip_line_comment()?,
+                _ => return Ok(()),
             }
         }
+    }
+
+    fn skip_comment(&mut self) -> Result<(), Error> {
+        self.advance()?; // consume leading '/'
+
+        match self.advance()? {
+            b'/' => self.skip_to_end_of_line(),
+            b'*' => loop {
+                if self.advance()? == b'*' && self.check(b'/')? {
+                    return Ok(());
+                }
+            },
+            _ => Err(self.error(ErrorType::InvalidComment)),
+        }
+    }
+
+    fn skip_line_comment(&mut self) -> Result<(), Error> {
+        self.advance()?; // consume leading '#'
+        self.skip_to_end_of_line()
+    }
+
+    fn skip_to_end_of_line(&mut self) -> Result<(), Error> {
+        while matches!(self.peek(), Ok(byte) if byte != b'\n') {
+            self.advance()?;
+        }
         Ok(())
     }
 }
@@ -245,6 +661,371 @@ impl Parser<'_> {
 //
 #[allow(dead_code)]
 pub fn parse_string<'a>(document: &'a String) -> Result<Value, Error> {
-    let mut parser = Parser::new(document);
-    parser.parse()
-}
\ No newline at end of file
+    let mut parser = Parser::new(SliceSource::new(document.as_bytes()));
+    let value = parser.parse()?;
+    parser.expect_eof()?;
+    Ok(value)
+}
+
+#[allow(dead_code)]
+pub fn parse_string_with_depth<'a>(document: &'a String, max_depth: usize) -> Result<Value, Error> {
+    let mut parser = Parser::with_max_depth(SliceSource::new(document.as_bytes()), max_depth);
+    let value = parser.parse()?;
+    parser.expect_eof()?;
+    Ok(value)
+}
+
+#[allow(dead_code)]
+pub fn parse_hjson_string<'a>(document: &'a String) -> Result<Value, Error> {
+    let mut parser = Parser::with_options(SliceSource::new(document.as_bytes()), DEFAULT_MAX_DEPTH, ParseOptions::hjson());
+    let value = parser.parse()?;
+    parser.expect_eof()?;
+    Ok(value)
+}
+
+#[allow(dead_code)]
+pub fn parse_iter<I: Iterator<Item = u8>>(bytes: I) -> Result<Value, Error> {
+    let mut parser = Parser::new(IterSource::new(bytes));
+    let value = parser.parse()?;
+    parser.expect_eof()?;
+    Ok(value)
+}
+
+#[allow(dead_code)]
+pub fn parse_reader<R: Read>(reader: R) -> Result<Value, Error> {
+    let mut parser = Parser::new(ReaderSource::new(reader)?);
+    let value = parser.parse()?;
+    parser.expect_eof()?;
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn string_value(document: &str) -> String {
+        match parse_string(&document.to_string()) {
+            Ok(Value::String { value }) => value,
+            Ok(other) => panic!("expected a string, got {:?}", other),
+            Err(error) => panic!("{}", error),
+        }
+    }
+
+    #[test]
+    fn decodes_simple_escapes() {
+        assert_eq!(string_value(r#""a\nb\tc\"d""#), "a\nb\tc\"d");
+    }
+
+    #[test]
+    fn decodes_unicode_escape() {
+        assert_eq!(string_value(r#""\u00e9""#), "é");
+    }
+
+    #[test]
+    fn decodes_surrogate_pair() {
+        assert_eq!(string_value("\"\\ud83d\\ude00\""), "\u{1F600}");
+    }
+
+    #[test]
+    fn rejects_unknown_escape() {
+        let result = parse_string(&r#""\q""#.to_string());
+        assert!(matches!(result, Err(Error { error: ErrorType::InvalidEscape { escape: b'q' }, .. })));
+    }
+
+    #[test]
+    fn rejects_unpaired_surrogate() {
+        let result = parse_string(&r#""\ud83d""#.to_string());
+        assert!(matches!(result, Err(Error { error: ErrorType::UnpairedSurrogate, .. })));
+    }
+
+    #[test]
+    fn rejects_nesting_past_the_depth_limit() {
+        let nested = "[".repeat(3) + &"]".repeat(3);
+        let result = parse_string_with_depth(&nested, 2);
+        assert!(matches!(result, Err(Error { error: ErrorType::MaxDepthExceeded, .. })));
+    }
+
+    #[test]
+    fn allows_nesting_up_to_the_depth_limit() {
+        let nested = "[".repeat(2) + &"]".repeat(2);
+        assert!(parse_string_with_depth(&nested, 2).is_ok());
+    }
+
+    #[test]
+    fn rejects_trailing_garbage_after_a_complete_value() {
+        let result = parse_string(&"true false".to_string());
+        assert!(matches!(result, Err(Error { error: ErrorType::TrailingCharacters, .. })));
+    }
+
+    #[test]
+    fn reports_one_based_line_and_column_on_the_first_line() {
+        let result = parse_string(&"[1, nul]".to_string());
+        assert!(matches!(result, Err(Error { line: 1, column: 9, .. })));
+    }
+
+    #[test]
+    fn tracks_line_and_column_across_newlines() {
+        let result = parse_string(&"[1,\n2,\nnul]".to_string());
+        assert!(matches!(result, Err(Error { line: 3, column: 5, .. })));
+    }
+
+    #[test]
+    fn parse_iter_parses_the_same_as_parse_string() {
+        let value = parse_iter("[1,2,3]".bytes()).ok();
+        let expected = parse_string(&"[1,2,3]".to_string()).ok();
+        assert!(matches!(value, Some(Value::Array { .. })));
+        assert_eq!(format!("{:?}", value), format!("{:?}", expected));
+    }
+
+    // a Read whose second call always fails, simulating a socket reset or disk error
+    // partway through a stream
+    struct FlakyReader {
+        calls: usize,
+    }
+
+    impl Read for FlakyReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.calls += 1;
+            if self.calls == 1 {
+                buf[0] = b'1';
+                Ok(1)
+            } else {
+                Err(io::Error::new(io::ErrorKind::Other, "boom"))
+            }
+        }
+    }
+
+    #[test]
+    fn parse_reader_propagates_io_errors_instead_of_panicking() {
+        let result = parse_reader(FlakyReader { calls: 0 });
+        assert!(matches!(result, Err(Error { error: ErrorType::Io(_), .. })));
+    }
+
+    #[test]
+    fn parse_reader_does_not_require_the_caller_to_buffer() {
+        let value = parse_reader("[1,2,3]".as_bytes());
+        assert!(matches!(value, Ok(Value::Array { .. })));
+    }
+
+    #[test]
+    fn hjson_mode_allows_comments_and_unquoted_keys() {
+        let value = match parse_hjson_string(&"{ // a comment\n a: 1 }".to_string()) {
+            Ok(Value::Dict { value }) => value,
+            Ok(other) => panic!("expected a dict, got {:?}", other),
+            Err(error) => panic!("{}", error),
+        };
+        assert!(matches!(value.get("a"), Some(Value::Integer { value: 1 })));
+    }
+
+    #[test]
+    fn strict_mode_rejects_comments_and_unquoted_keys() {
+        assert!(matches!(
+            parse_string(&"{ // nope\n }".to_string()),
+            Err(Error { error: ErrorType::ExpectedDictKey, .. })
+        ));
+        assert!(matches!(
+            parse_string(&"{ a: 1 }".to_string()),
+            Err(Error { error: ErrorType::ExpectedDictKey, .. })
+        ));
+    }
+
+    #[test]
+    fn strict_mode_rejects_trailing_commas() {
+        assert!(matches!(
+            parse_string(&"[1,2,]".to_string()),
+            Err(Error { error: ErrorType::ExpectedArrayCloseOrComma, .. })
+        ));
+        assert!(matches!(
+            parse_string(&"{\"a\":1,}".to_string()),
+            Err(Error { error: ErrorType::ExpectedDictCloseOrComma, .. })
+        ));
+    }
+
+    #[test]
+    fn hjson_mode_allows_trailing_commas() {
+        assert!(parse_hjson_string(&"[1,2,]".to_string()).is_ok());
+        assert!(parse_hjson_string(&"{\"a\":1,}".to_string()).is_ok());
+    }
+
+    #[test]
+    fn parses_integers_and_floats() {
+        assert!(matches!(parse_string(&"42".to_string()), Ok(Value::Integer { value: 42 })));
+        assert!(matches!(parse_string(&"-7".to_string()), Ok(Value::Integer { value: -7 })));
+        assert!(matches!(parse_string(&"3.25".to_string()), Ok(Value::Float { value }) if value == 3.25));
+        assert!(matches!(parse_string(&"1e3".to_string()), Ok(Value::Float { value }) if value == 1000.0));
+    }
+
+    #[test]
+    fn widens_integers_that_overflow_i64_to_a_float() {
+        assert!(matches!(parse_string(&"99999999999999999999".to_string()), Ok(Value::Float { .. })));
+    }
+
+    #[test]
+    fn rejects_malformed_number_literals() {
+        assert!(matches!(
+            parse_string(&"1.".to_string()),
+            Err(Error { error: ErrorType::InvalidNumber { .. }, .. })
+        ));
+        assert!(matches!(
+            parse_string(&"01".to_string()),
+            Err(Error { error: ErrorType::TrailingCharacters, .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_number_literals_that_overflow_to_infinity() {
+        assert!(matches!(
+            parse_string(&"1e400".to_string()),
+            Err(Error { error: ErrorType::InvalidNumber { .. }, .. })
+        ));
+    }
+
+    #[test]
+    fn serializes_each_value_kind() {
+        assert_eq!(to_string(&Value::Null), "null");
+        assert_eq!(to_string(&Value::Boolean { value: true }), "true");
+        assert_eq!(to_string(&Value::Integer { value: 42 }), "42");
+        assert_eq!(to_string(&Value::String { value: "a\"b".to_string() }), r#""a\"b""#);
+        assert_eq!(to_string(&Value::Array { value: vec![Value::Integer { value: 1 }, Value::Null] }), "[1,null]");
+    }
+
+    #[test]
+    fn serializes_whole_number_floats_with_a_decimal_point() {
+        assert_eq!(to_string(&Value::Float { value: 2.0 }), "2.0");
+        assert_eq!(to_string(&Value::Float { value: 2.5 }), "2.5");
+    }
+
+    #[test]
+    fn pretty_printing_indents_nested_containers() {
+        let value = Value::Array { value: vec![Value::Integer { value: 1 }] };
+        assert_eq!(to_string_pretty(&value, 2), "[\n  1\n]");
+    }
+
+    #[test]
+    fn serialized_floats_round_trip_back_to_a_float() {
+        let original = Value::Float { value: 2.0 };
+        let value = match parse_string(&to_string(&original)) {
+            Ok(value) => value,
+            Err(error) => panic!("{}", error),
+        };
+        assert!(matches!(value, Value::Float { value } if value == 2.0));
+    }
+}
+
+//
+//  Serializer stuff
+//
+#[allow(dead_code)]
+pub fn to_string(value: &Value) -> String {
+    let mut out = String::new();
+    write_value(value, &mut out);
+    out
+}
+
+// indent is the number of spaces per nesting level
+#[allow(dead_code)]
+pub fn to_string_pretty(value: &Value, indent: usize) -> String {
+    let mut out = String::new();
+    write_value_pretty(value, &mut out, indent, 0);
+    out
+}
+
+fn write_value(value: &Value, out: &mut String) {
+    match value {
+        Value::Null => out.push_str("null"),
+        Value::Boolean { value } => out.push_str(if *value { "true" } else { "false" }),
+        Value::Integer { value } => out.push_str(&value.to_string()),
+        Value::Float { value } => write_float(*value, out),
+        Value::String { value } => write_escaped_string(value, out),
+        Value::Array { value } => {
+            out.push('[');
+            for (index, item) in value.iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                write_value(item, out);
+            }
+            out.push(']');
+        }
+        Value::Dict { value } => {
+            out.push('{');
+            for (index, (key, item)) in value.iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                write_escaped_string(key, out);
+                out.push(':');
+                write_value(item, out);
+            }
+            out.push('}');
+        }
+    }
+}
+
+// f64::to_string() omits the decimal point for whole numbers (2.0 -> "2"), which would
+// reparse as a Value::Integer; append ".0" so a finite float always round-trips as a float
+// (NaN and infinities come out as "NaN.0" / "inf.0", which do not parse back)
+fn write_float(value: f64, out: &mut String) {
+    let formatted = value.to_string();
+    out.push_str(&formatted);
+    if !formatted.contains('.') && !formatted.contains('e') && !formatted.contains('E') {
+        out.push_str(".0");
+    }
+}
+
+fn write_value_pretty(value: &Value, out: &mut String, indent: usize, depth: usize) {
+    match value {
+        Value::Array { value } if !value.is_empty() => {
+            out.push('[');
+            for (index, item) in value.iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                out.push('\n');
+                push_indent(out, indent, depth + 1);
+                write_value_pretty(item, out, indent, depth + 1);
+            }
+            out.push('\n');
+            push_indent(out, indent, depth);
+            out.push(']');
+        }
+        Value::Dict { value } if !value.is_empty() => {
+            out.push('{');
+            for (index, (key, item)) in value.iter().enumerate() {
+                if index > 0 {
+                    out.push(',');
+                }
+                out.push('\n');
+                push_indent(out, indent, depth + 1);
+                write_escaped_string(key, out);
+                out.push_str(": ");
+                write_value_pretty(item, out, indent, depth + 1);
+            }
+            out.push('\n');
+            push_indent(out, indent, depth);
+            out.push('}');
+        }
+        other => write_value(other, out),
+    }
+}
+
+fn push_indent(out: &mut String, indent: usize, depth: usize) {
+    for _ in 0..(indent * depth) {
+        out.push(' ');
+    }
+}
+
+fn write_escaped_string(value: &str, out: &mut String) {
+    out.push('"');
+
+    for ch in value.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            ch if (ch as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => out.push(ch),
+        }
+    }
+
+    out.push('"');
+}